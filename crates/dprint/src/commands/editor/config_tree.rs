@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+
+use crate::arg_parser::CliArgs;
+use crate::configuration::resolve_config_from_path;
+use crate::configuration::ResolvedConfigPath;
+use crate::environment::Environment;
+use crate::plugin_cache::PluginCache;
+use crate::plugins::PluginResolver;
+use crate::resolution::resolve_plugins_scope;
+use crate::resolution::resolve_plugins_scope_incremental;
+use crate::resolution::PluginsScope;
+use crate::resolution::ResolvePluginsOptions;
+use crate::utils::ResolvedPath;
+
+/// The config file names dprint looks for when walking up from a document
+/// towards the root, same as the CLI's own config discovery.
+const CONFIG_FILE_NAMES: [&str; 2] = ["dprint.json", ".dprintrc.json"];
+
+/// Resolves and caches, per ancestor config file, the `PluginsScope` that
+/// governs a given document. This mirrors `PluginsAndPathsResolver::resolve_sub_config`
+/// on the CLI side, but resolves lazily per-document instead of eagerly
+/// walking the whole project up front.
+pub struct ConfigTree<TEnvironment: Environment> {
+  args: CliArgs,
+  environment: TEnvironment,
+  plugin_resolver: Rc<PluginResolver<TEnvironment>>,
+  /// Loaded once and shared across every `scope_for_path` resolution this
+  /// tree ever does, so concurrently-resolving documents read and write the
+  /// same in-memory cache state instead of each saving their own snapshot
+  /// and clobbering one another's newly-cached entries.
+  plugin_cache: PluginCache<TEnvironment>,
+  /// Keyed by the canonicalized config file path. An `RwLock` so that many
+  /// concurrent `scope_for_path` lookups (reads) don't block each other;
+  /// only resolving a new/invalidated scope takes the write lock. Each entry
+  /// also stamps the `dirty` generation it was resolved against, so a
+  /// resolve that was already stale by the time it finished (because another
+  /// `invalidate` landed while it was in flight) doesn't get mistaken for
+  /// up to date -- see `invalidate`.
+  scopes: RwLock<HashMap<PathBuf, (Rc<PluginsScope<TEnvironment>>, u64)>>,
+  /// Per config path, a generation counter bumped on every `invalidate` call.
+  /// A path absent from this map is at generation 0. Never reset, so two
+  /// invalidations of the same path always produce distinct generations.
+  dirty: RwLock<HashMap<PathBuf, u64>>,
+}
+
+impl<TEnvironment: Environment> ConfigTree<TEnvironment> {
+  pub fn new(args: CliArgs, environment: TEnvironment, plugin_resolver: Rc<PluginResolver<TEnvironment>>) -> Self {
+    let plugin_cache = PluginCache::load(environment.clone());
+    Self {
+      args,
+      environment,
+      plugin_resolver,
+      plugin_cache,
+      scopes: Default::default(),
+      dirty: Default::default(),
+    }
+  }
+
+  /// Gets the resolved scope that governs `file_path`, resolving and caching
+  /// it if this is the first time it's been requested or if it was
+  /// invalidated since it was last resolved.
+  pub async fn scope_for_path(&self, file_path: &Path) -> Result<Rc<PluginsScope<TEnvironment>>> {
+    let config_path = self.find_nearest_config_file(file_path)?;
+    let cached = self.scopes.read().await.get(&config_path).cloned();
+    let generation = self.dirty.read().await.get(&config_path).copied().unwrap_or(0);
+
+    if let Some((scope, resolved_generation)) = &cached {
+      if *resolved_generation == generation {
+        return Ok(scope.clone());
+      }
+    }
+
+    // either this is the first time we've seen this config file, or a watched
+    // config file changed -- either way, only the plugins whose own config
+    // actually changed will be re-initialized (see `resolve_plugins_scope_incremental`)
+    let previous = cached.as_ref().map(|(scope, _)| scope.as_ref());
+    let scope = Rc::new(self.resolve_scope(&config_path, previous).await?);
+    // stamp the scope with the generation it was resolved against, not the
+    // current one -- if another `invalidate` landed while we were resolving,
+    // the generation read above is already stale, and stamping it (rather
+    // than whatever `dirty` holds now) makes the next `scope_for_path` call
+    // see a mismatch and redo the resolve instead of serving a scope that
+    // missed that invalidation
+    self.scopes.write().await.insert(config_path.clone(), (scope.clone(), generation));
+    Ok(scope)
+  }
+
+  /// Marks the cached scope for a config file that changed on disk as
+  /// needing to be re-resolved, without throwing away what's still valid --
+  /// the next `scope_for_path` call re-resolves just that subtree, reusing
+  /// whichever plugins didn't actually change. Bumping a per-path generation
+  /// counter (rather than a single dirty flag) means an invalidation that
+  /// lands while a resolve for the same path is already in flight isn't lost:
+  /// that resolve gets stamped with the generation it started from, which no
+  /// longer matches, so the next call redoes it.
+  pub async fn invalidate(&self, config_file_path: &Path) {
+    if let Ok(config_file_path) = self.environment.canonicalize(config_file_path) {
+      let mut dirty = self.dirty.write().await;
+      *dirty.entry(config_file_path).or_insert(0) += 1;
+    }
+  }
+
+  async fn resolve_scope(&self, config_path: &Path, previous: Option<&PluginsScope<TEnvironment>>) -> Result<PluginsScope<TEnvironment>> {
+    let resolved_config_path = ResolvedConfigPath {
+      base_path: config_path.parent().unwrap(),
+      resolved_path: ResolvedPath::local(config_path.to_path_buf()),
+    };
+    let config = Rc::new(resolve_config_from_path(&resolved_config_path, &self.environment).await?);
+    let options = ResolvePluginsOptions {
+      check_top_level_unknown_property_diagnostics: self.args.plugins.is_empty(),
+    };
+    let scope = match previous {
+      Some(previous) => resolve_plugins_scope_incremental(config, &self.environment, &self.plugin_resolver, &options, previous, &self.plugin_cache).await?,
+      None => resolve_plugins_scope(config, &self.environment, &self.plugin_resolver, &options, &self.plugin_cache).await?,
+    };
+    Ok(scope)
+  }
+
+  /// Walks up from `file_path`'s directory looking for the nearest config
+  /// file, falling back to the config resolved from the CLI args (which may
+  /// itself point at an explicit `--config` or the current directory's
+  /// config file) when none is found above the document.
+  fn find_nearest_config_file(&self, file_path: &Path) -> Result<PathBuf> {
+    match find_ancestor_config_file(file_path, &CONFIG_FILE_NAMES, |path| self.environment.path_exists(path)) {
+      Some(candidate) => self.environment.canonicalize(&candidate),
+      // no ancestor config file -- fall back to whatever the CLI args resolve to
+      None => {
+        let fallback = self.args.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAMES[0]));
+        self.environment.canonicalize(&fallback)
+      }
+    }
+  }
+}
+
+/// The ancestor-walk part of `find_nearest_config_file`, pulled out as a pure
+/// function (no `Environment` needed) so it can be tested directly.
+fn find_ancestor_config_file(file_path: &Path, config_file_names: &[&str], path_exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+  let mut current_dir = file_path.parent();
+  while let Some(dir) = current_dir {
+    for config_file_name in config_file_names {
+      let candidate = dir.join(config_file_name);
+      if path_exists(&candidate) {
+        return Some(candidate);
+      }
+    }
+    current_dir = dir.parent();
+  }
+  None
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashSet;
+
+  use super::*;
+
+  #[test]
+  fn finds_config_file_in_immediate_parent() {
+    let existing: HashSet<PathBuf> = [PathBuf::from("/project/dprint.json")].into_iter().collect();
+    let found = find_ancestor_config_file(Path::new("/project/src/main.ts"), &CONFIG_FILE_NAMES, |p| existing.contains(p));
+    assert_eq!(found, Some(PathBuf::from("/project/dprint.json")));
+  }
+
+  #[test]
+  fn walks_up_past_directories_with_no_config() {
+    let existing: HashSet<PathBuf> = [PathBuf::from("/project/dprint.json")].into_iter().collect();
+    let found = find_ancestor_config_file(Path::new("/project/src/nested/deep/main.ts"), &CONFIG_FILE_NAMES, |p| existing.contains(p));
+    assert_eq!(found, Some(PathBuf::from("/project/dprint.json")));
+  }
+
+  #[test]
+  fn prefers_the_nearest_ancestor_config_file() {
+    let existing: HashSet<PathBuf> = [PathBuf::from("/project/dprint.json"), PathBuf::from("/project/src/dprint.json")].into_iter().collect();
+    let found = find_ancestor_config_file(Path::new("/project/src/main.ts"), &CONFIG_FILE_NAMES, |p| existing.contains(p));
+    assert_eq!(found, Some(PathBuf::from("/project/src/dprint.json")));
+  }
+
+  #[test]
+  fn checks_both_config_file_names_before_walking_up() {
+    let existing: HashSet<PathBuf> = [PathBuf::from("/project/dprint.json"), PathBuf::from("/project/src/.dprintrc.json")].into_iter().collect();
+    let found = find_ancestor_config_file(Path::new("/project/src/main.ts"), &CONFIG_FILE_NAMES, |p| existing.contains(p));
+    assert_eq!(found, Some(PathBuf::from("/project/src/.dprintrc.json")));
+  }
+
+  #[test]
+  fn returns_none_when_no_ancestor_has_a_config_file() {
+    let found = find_ancestor_config_file(Path::new("/project/src/main.ts"), &CONFIG_FILE_NAMES, |_| false);
+    assert_eq!(found, None);
+  }
+}
@@ -1,7 +1,17 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use dprint_core::plugins::process::start_parent_process_checker_task;
+use dprint_core::plugins::HostFormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::task::LocalSet;
 use tower_lsp::lsp_types::DidChangeTextDocumentParams;
+use tower_lsp::lsp_types::DidChangeWatchedFilesParams;
 use tower_lsp::lsp_types::DidCloseTextDocumentParams;
 use tower_lsp::lsp_types::DidOpenTextDocumentParams;
 use tower_lsp::lsp_types::DocumentFormattingParams;
@@ -10,7 +20,14 @@ use tower_lsp::lsp_types::InitializeParams;
 use tower_lsp::lsp_types::InitializeResult;
 use tower_lsp::lsp_types::InitializedParams;
 use tower_lsp::lsp_types::MessageType;
+use tower_lsp::lsp_types::OneOf;
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::ServerCapabilities;
+use tower_lsp::lsp_types::TextDocumentSyncCapability;
+use tower_lsp::lsp_types::TextDocumentSyncKind;
 use tower_lsp::lsp_types::TextEdit;
+use tower_lsp::lsp_types::Url;
 use tower_lsp::Client;
 use tower_lsp::LspService;
 use tower_lsp::Server;
@@ -19,6 +36,9 @@ use crate::arg_parser::CliArgs;
 use crate::environment::Environment;
 use crate::plugins::PluginResolver;
 
+use super::config_tree::ConfigTree;
+use super::text_diff::diff_texts;
+
 pub async fn run_language_server<TEnvironment: Environment>(
   args: &CliArgs,
   environment: &TEnvironment,
@@ -27,14 +47,79 @@ pub async fn run_language_server<TEnvironment: Environment>(
   let stdin = tokio::io::stdin();
   let stdout = tokio::io::stdout();
 
-  let (service, socket) = LspService::new(|client| Backend { client });
-  Server::new(stdin, stdout, socket).serve(service).await;
+  let config_tree = ConfigTree::new(args.clone(), environment.clone(), plugin_resolver.clone());
+  let (command_sender, command_receiver) = mpsc::unbounded_channel();
+
+  // ConfigTree (and everything it resolves through `PluginResolver`) is built
+  // on `Rc`, since dprint's plugin resolution machinery is single-threaded by
+  // design -- it's never been `Send`. `tower_lsp::LanguageServer` requires
+  // `Send + Sync` on the whole `Backend`, so rather than forcing that `Rc`
+  // chain into `Arc` (which `PluginResolver` itself doesn't support), `Backend`
+  // only holds a `Send + Sync` channel handle and forwards every request as a
+  // `Command` to this worker, which owns all the `!Send` state and runs on a
+  // `LocalSet` on the current thread.
+  let local_set = LocalSet::new();
+  local_set
+    .run_until(async move {
+      let (service, socket) = LspService::new(move |client| {
+        tokio::task::spawn_local(run_worker(config_tree, client.clone(), command_receiver));
+        Backend { client, commands: command_sender }
+      });
+      Server::new(stdin, stdout, socket).serve(service).await;
+    })
+    .await;
 
   Ok(())
 }
 
+/// An open document as tracked by the language server.
+struct Document {
+  text: String,
+  version: i32,
+  language_id: String,
+}
+
+/// A request forwarded from `Backend` (which only needs to be `Send + Sync`)
+/// to the worker task that owns the `!Send` `ConfigTree` and document state.
+enum Command {
+  DidOpen {
+    uri: Url,
+    text: String,
+    version: i32,
+    language_id: String,
+  },
+  DidChange {
+    uri: Url,
+    text: String,
+    version: i32,
+  },
+  DidClose {
+    uri: Url,
+  },
+  InvalidateConfig {
+    config_file_path: PathBuf,
+  },
+  Format {
+    uri: Url,
+    range: Option<Range>,
+    respond: oneshot::Sender<Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error>>,
+  },
+}
+
 struct Backend {
   client: Client,
+  commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Backend {
+  async fn format(&self, uri: Url, range: Option<Range>) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+    let (respond, receiver) = oneshot::channel();
+    if self.commands.send(Command::Format { uri, range, respond }).is_err() {
+      return Ok(None);
+    }
+    // the worker only drops `respond` without sending if it's shutting down
+    receiver.await.unwrap_or(Ok(None))
+  }
 }
 
 #[tower_lsp::async_trait]
@@ -43,7 +128,15 @@ impl tower_lsp::LanguageServer for Backend {
     if let Some(parent_id) = params.process_id {
       start_parent_process_checker_task(parent_id);
     }
-    Ok(InitializeResult::default())
+    Ok(InitializeResult {
+      capabilities: ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+      },
+      ..Default::default()
+    })
   }
 
   async fn initialized(&self, _: InitializedParams) {
@@ -55,24 +148,179 @@ impl tower_lsp::LanguageServer for Backend {
   }
 
   async fn did_open(&self, params: DidOpenTextDocumentParams) {
-    // todo, keep track of
+    let document = params.text_document;
+    let _ = self.commands.send(Command::DidOpen {
+      uri: document.uri,
+      text: document.text,
+      version: document.version,
+      language_id: document.language_id,
+    });
   }
 
   async fn did_change(&self, params: DidChangeTextDocumentParams) {
-    // todo
+    // we only ever advertise full document sync, so the last change contains the whole text
+    let Some(change) = params.content_changes.into_iter().last() else {
+      return;
+    };
+    let _ = self.commands.send(Command::DidChange {
+      uri: params.text_document.uri,
+      text: change.text,
+      version: params.text_document.version,
+    });
   }
 
   async fn did_close(&self, params: DidCloseTextDocumentParams) {
-    // todo
+    let _ = self.commands.send(Command::DidClose { uri: params.text_document.uri });
+  }
+
+  async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+    for change in params.changes {
+      if let Ok(config_file_path) = change.uri.to_file_path() {
+        let _ = self.commands.send(Command::InvalidateConfig { config_file_path });
+      }
+    }
   }
 
   async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
-    // todo
-    Ok(None)
+    self.format(params.text_document.uri, None).await
   }
 
   async fn range_formatting(&self, params: DocumentRangeFormattingParams) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
-    // todo
-    Ok(None)
+    self.format(params.text_document.uri, Some(params.range)).await
+  }
+}
+
+/// Owns the `!Send` `ConfigTree` and open-document state for the whole
+/// connection, processing commands one at a time but spawning the
+/// potentially slow ones (resolving a scope, formatting) onto the same
+/// `LocalSet` so they don't block commands for unrelated documents.
+async fn run_worker<TEnvironment: Environment>(config_tree: ConfigTree<TEnvironment>, client: Client, mut commands: mpsc::UnboundedReceiver<Command>) {
+  let config_tree = Rc::new(config_tree);
+  let documents: Rc<RefCell<HashMap<Url, Rc<Document>>>> = Default::default();
+
+  while let Some(command) = commands.recv().await {
+    match command {
+      Command::DidOpen { uri, text, version, language_id } => {
+        documents.borrow_mut().insert(uri.clone(), Rc::new(Document { text, version, language_id }));
+        tokio::task::spawn_local(warm_scope(config_tree.clone(), client.clone(), uri));
+      }
+      Command::DidChange { uri, text, version } => {
+        let language_id = documents.borrow().get(&uri).map(|d| d.language_id.clone()).unwrap_or_default();
+        documents.borrow_mut().insert(uri.clone(), Rc::new(Document { text, version, language_id }));
+        tokio::task::spawn_local(warm_scope(config_tree.clone(), client.clone(), uri));
+      }
+      Command::DidClose { uri } => {
+        documents.borrow_mut().remove(&uri);
+      }
+      Command::InvalidateConfig { config_file_path } => {
+        let config_tree = config_tree.clone();
+        tokio::task::spawn_local(async move { config_tree.invalidate(&config_file_path).await });
+      }
+      Command::Format { uri, range, respond } => {
+        let config_tree = config_tree.clone();
+        let client = client.clone();
+        let documents = documents.clone();
+        tokio::task::spawn_local(async move {
+          let result = format_document(&config_tree, &client, &documents, &uri, range).await;
+          let _ = respond.send(result);
+        });
+      }
+    }
+  }
+}
+
+/// Resolves (and caches) the scope governing `uri` right away, so config
+/// errors for a just-opened or just-edited document surface immediately
+/// instead of waiting for the user's first format, and so that first
+/// format doesn't pay the resolution cost itself.
+async fn warm_scope<TEnvironment: Environment>(config_tree: Rc<ConfigTree<TEnvironment>>, client: Client, uri: Url) {
+  let Ok(file_path) = uri.to_file_path() else {
+    return;
+  };
+  if let Err(err) = config_tree.scope_for_path(&file_path).await {
+    client.log_message(MessageType::ERROR, err.to_string()).await;
+  }
+}
+
+async fn format_document<TEnvironment: Environment>(
+  config_tree: &ConfigTree<TEnvironment>,
+  client: &Client,
+  documents: &RefCell<HashMap<Url, Rc<Document>>>,
+  uri: &Url,
+  range: Option<Range>,
+) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+  // take a cheap snapshot of the document up front so formatting (which can
+  // take a while) doesn't hold the borrow, then verify it's still current
+  // before returning edits against it
+  let snapshot = match documents.borrow().get(uri) {
+    Some(document) => document.clone(),
+    None => return Ok(None),
+  };
+  let file_path = match uri.to_file_path() {
+    Ok(file_path) => file_path,
+    Err(()) => return Ok(None),
+  };
+
+  let scope = match config_tree.scope_for_path(&file_path).await {
+    Ok(scope) => scope,
+    Err(err) => {
+      client.log_message(MessageType::ERROR, err.to_string()).await;
+      return Ok(None);
+    }
+  };
+
+  let format_range = range.map(|range| lsp_range_to_byte_range(&snapshot.text, range));
+  let request = HostFormatRequest {
+    file_path,
+    file_text: snapshot.text.clone(),
+    range: format_range,
+    override_config: Default::default(),
+    token: Arc::new(NullCancellationToken),
+  };
+
+  let new_text = match scope.format(request).await {
+    Ok(Some(new_text)) => new_text,
+    Ok(None) => return Ok(None),
+    Err(err) => {
+      client.log_message(MessageType::ERROR, err.to_string()).await;
+      return Ok(None);
+    }
+  };
+
+  // the document may have been edited while we were off formatting -- drop
+  // the result rather than hand back edits against text the editor no
+  // longer has open
+  let is_stale = match documents.borrow().get(uri) {
+    Some(current) => current.version != snapshot.version,
+    None => true,
+  };
+  if is_stale {
+    return Ok(None);
+  }
+
+  let edits = diff_texts(&snapshot.text, &new_text);
+  Ok(if edits.is_empty() { None } else { Some(edits) })
+}
+
+/// Converts an LSP range (UTF-16 based) into a byte range within `text`.
+fn lsp_range_to_byte_range(text: &str, range: Range) -> std::ops::Range<usize> {
+  lsp_position_to_byte_offset(text, range.start)..lsp_position_to_byte_offset(text, range.end)
+}
+
+fn lsp_position_to_byte_offset(text: &str, position: Position) -> usize {
+  let mut line_start = 0;
+  for (current_line, line) in text.split_inclusive('\n').enumerate() {
+    if current_line as u32 == position.line {
+      let mut utf16_count = 0u32;
+      for (byte_index, c) in line.char_indices() {
+        if utf16_count >= position.character {
+          return line_start + byte_index;
+        }
+        utf16_count += c.len_utf16() as u32;
+      }
+      return line_start + line.len();
+    }
+    line_start += line.len();
   }
+  text.len()
 }
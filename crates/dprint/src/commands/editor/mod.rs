@@ -0,0 +1,5 @@
+mod config_tree;
+mod language_server;
+mod text_diff;
+
+pub use language_server::run_language_server;
@@ -0,0 +1,234 @@
+use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::Range;
+use tower_lsp::lsp_types::TextEdit;
+
+/// Precomputes line boundaries of a text so that line numbers can be turned
+/// into LSP (UTF-16 based) `Position`s without rescanning the text.
+pub struct LineIndex {
+  /// UTF-16 code-unit offset of the start of every complete line, i.e. one
+  /// entry per `\n` encountered, plus the implicit line 0 at offset 0.
+  utf16_line_starts: Vec<u32>,
+  text_utf16_len: u32,
+  ends_with_newline: bool,
+}
+
+impl LineIndex {
+  pub fn new(text: &str) -> Self {
+    let mut utf16_line_starts = vec![0u32];
+    let mut offset = 0u32;
+    let mut ends_with_newline = true; // an empty file has no partial trailing line
+    for c in text.chars() {
+      offset += c.len_utf16() as u32;
+      ends_with_newline = c == '\n';
+      if ends_with_newline {
+        utf16_line_starts.push(offset);
+      }
+    }
+    Self {
+      utf16_line_starts,
+      text_utf16_len: offset,
+      ends_with_newline,
+    }
+  }
+
+  /// The position at the start of the given line, where `line` is an index
+  /// into the segments produced by `str::split_inclusive('\n')`.
+  pub fn line_start(&self, line: usize) -> Position {
+    Position::new(line as u32, 0)
+  }
+
+  /// The position at the very end of the text.
+  pub fn end_position(&self) -> Position {
+    if self.ends_with_newline {
+      Position::new(self.utf16_line_starts.len() as u32 - 1, 0)
+    } else {
+      let last_line_start = *self.utf16_line_starts.last().unwrap();
+      Position::new(self.utf16_line_starts.len() as u32 - 1, self.text_utf16_len - last_line_start)
+    }
+  }
+}
+
+/// Diffs `old_text` against `new_text` line-by-line and returns the minimal
+/// set of `TextEdit`s that turns one into the other, instead of replacing
+/// the whole document. Lines (including their line ending) are compared as
+/// opaque tokens, so CRLF vs LF differences show up as real changes.
+pub fn diff_texts(old_text: &str, new_text: &str) -> Vec<TextEdit> {
+  if old_text == new_text {
+    return Vec::new();
+  }
+
+  let old_lines: Vec<&str> = old_text.split_inclusive('\n').collect();
+  let new_lines: Vec<&str> = new_text.split_inclusive('\n').collect();
+  let old_index = LineIndex::new(old_text);
+  let matches = matching_lines(&old_lines, &new_lines);
+
+  let mut edits = Vec::new();
+  let mut old_pos = 0;
+  let mut new_pos = 0;
+  for (old_match, new_match) in matches.into_iter().chain(std::iter::once((old_lines.len(), new_lines.len()))) {
+    if old_match > old_pos || new_match > new_pos {
+      let start = old_index.line_start(old_pos);
+      let end = if old_match < old_lines.len() {
+        old_index.line_start(old_match)
+      } else {
+        old_index.end_position()
+      };
+      edits.push(TextEdit {
+        range: Range::new(start, end),
+        new_text: new_lines[new_pos..new_match].concat(),
+      });
+    }
+    old_pos = old_match + 1;
+    new_pos = new_match + 1;
+  }
+
+  edits
+}
+
+/// Computes the longest common subsequence of identical lines between
+/// `old_lines` and `new_lines`, returning the matched `(old_index, new_index)`
+/// pairs in increasing order.
+///
+/// Uses Myers' O(ND) diff algorithm rather than the textbook O(n*m) LCS DP
+/// table: `D` is the number of changed lines, so a large file that only
+/// changed in one spot stays cheap, whereas the DP table's cost (and its
+/// `n*m`-cell allocation) grows with the file size regardless of how small
+/// the actual diff is.
+fn matching_lines(old_lines: &[&str], new_lines: &[&str]) -> Vec<(usize, usize)> {
+  let n = old_lines.len() as isize;
+  let m = new_lines.len() as isize;
+  let max_d = n + m;
+  if max_d == 0 {
+    return Vec::new();
+  }
+  let offset = max_d;
+  let size = 2 * max_d + 1;
+
+  // `trace[d]` is the frontier of furthest-reaching x values after d edits,
+  // indexed by `k - (-max_d)`. Keeping one snapshot per d (rather than
+  // mutating a single array) is what lets us walk the trace back into a
+  // match list afterwards; its total size is O(D*(n+m)), not O(n*m).
+  let mut trace: Vec<Vec<isize>> = Vec::new();
+  let mut v = vec![0isize; size as usize];
+  let mut found_at = max_d;
+  'search: for d in 0..=max_d {
+    trace.push(v.clone());
+    let mut k = -d;
+    while k <= d {
+      let idx = (k + offset) as usize;
+      let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+        v[idx + 1]
+      } else {
+        v[idx - 1] + 1
+      };
+      let mut y = x - k;
+      while x < n && y < m && old_lines[x as usize] == new_lines[y as usize] {
+        x += 1;
+        y += 1;
+      }
+      v[idx] = x;
+      if x >= n && y >= m {
+        found_at = d;
+        break 'search;
+      }
+      k += 2;
+    }
+  }
+
+  // walk the recorded frontiers back from (n, m) to (0, 0), collecting every
+  // diagonal (equal-line) step along the way
+  let mut matches = Vec::new();
+  let (mut x, mut y) = (n, m);
+  for d in (0..=found_at).rev() {
+    let v = &trace[d as usize];
+    let k = x - y;
+    let idx = (k + offset) as usize;
+    let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { k + 1 } else { k - 1 };
+    let prev_idx = (prev_k + offset) as usize;
+    let prev_x = v[prev_idx];
+    let prev_y = prev_x - prev_k;
+    while x > prev_x && y > prev_y {
+      x -= 1;
+      y -= 1;
+      matches.push((x as usize, y as usize));
+    }
+    x = prev_x;
+    y = prev_y;
+  }
+  matches.reverse();
+  matches
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn no_diff_for_identical_text() {
+    assert_eq!(diff_texts("same\ntext\n", "same\ntext\n"), Vec::new());
+  }
+
+  #[test]
+  fn single_line_change_in_the_middle() {
+    let old = "one\ntwo\nthree\n";
+    let new = "one\ntwo-changed\nthree\n";
+    let edits = diff_texts(old, new);
+    // only the changed line should show up as an edit, not the whole file
+    assert_eq!(
+      edits,
+      vec![TextEdit {
+        range: Range::new(Position::new(1, 0), Position::new(2, 0)),
+        new_text: "two-changed\n".to_string(),
+      }]
+    );
+  }
+
+  #[test]
+  fn separate_insertion_and_deletion_produce_separate_edits() {
+    let old = "a\nb\nc\nd\n";
+    let new = "a\nc\nd\ne\n";
+    let edits = diff_texts(old, new);
+    assert_eq!(
+      edits,
+      vec![
+        TextEdit {
+          range: Range::new(Position::new(1, 0), Position::new(2, 0)),
+          new_text: String::new(),
+        },
+        TextEdit {
+          range: Range::new(Position::new(4, 0), Position::new(4, 0)),
+          new_text: "e\n".to_string(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn change_on_a_trailing_line_without_a_newline() {
+    let old = "a\nb";
+    let new = "a\nb-changed";
+    let edits = diff_texts(old, new);
+    assert_eq!(
+      edits,
+      vec![TextEdit {
+        range: Range::new(Position::new(1, 0), Position::new(1, 1)),
+        new_text: "b-changed".to_string(),
+      }]
+    );
+  }
+
+  #[test]
+  fn line_index_tracks_utf16_positions() {
+    // "é" is one UTF-16 code unit, so the second line still starts at offset 2
+    let index = LineIndex::new("é\nrest\n");
+    assert_eq!(index.utf16_line_starts, vec![0, 2, 7]);
+    assert_eq!(index.line_start(1), Position::new(1, 0));
+    assert_eq!(index.end_position(), Position::new(2, 0));
+  }
+
+  #[test]
+  fn line_index_end_position_without_trailing_newline() {
+    let index = LineIndex::new("abc");
+    assert_eq!(index.end_position(), Position::new(0, 3));
+  }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use dprint_core::plugins::FileMatchingInfo;
+use dprint_core::plugins::PluginInfo;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::environment::Environment;
+
+const CACHE_FILE_NAME: &str = "plugin-cache.msgpackz";
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+  info: PluginInfo,
+  file_matching: FileMatchingInfo,
+}
+
+#[derive(Default)]
+struct CacheState {
+  entries: HashMap<u64, Vec<u8>>,
+  dirty_keys: HashSet<u64>,
+}
+
+/// A persistent, cross-process cache of resolved plugin state (the plugin's
+/// `PluginInfo` and its resolved `FileMatchingInfo`), keyed by a hash of the
+/// plugin's identity plus the config that was used to resolve it (see
+/// `incremental_hash` in `resolution.rs`).
+///
+/// Deliberately doesn't cache config diagnostics: computing them means
+/// initializing the plugin and running the check, which has real cost and
+/// logs to stderr, so it stays a lazy, in-memory-only concern of
+/// `PluginWithConfig` that only runs once something actually tries to format.
+///
+/// Stored as MessagePack, Brotli-compressed, under the environment's cache
+/// directory. Each entry is encoded independently so that a corrupt or
+/// outdated entry for one plugin (e.g. after a `PluginInfo` shape change)
+/// only loses that one entry instead of the whole cache.
+///
+/// Callers load a single instance and share it (by reference) across every
+/// resolution that might run concurrently -- e.g. the language server keeps
+/// one per `ConfigTree`, and a CLI run keeps one across all of its sub-config
+/// resolutions -- rather than each resolution loading and saving its own
+/// snapshot, which would let whichever one saved last silently clobber
+/// another's newly-cached entries.
+pub struct PluginCache<TEnvironment: Environment> {
+  environment: TEnvironment,
+  file_path: PathBuf,
+  state: Mutex<CacheState>,
+}
+
+impl<TEnvironment: Environment> PluginCache<TEnvironment> {
+  pub fn load(environment: TEnvironment) -> Self {
+    let file_path = environment.get_cache_dir().join(CACHE_FILE_NAME);
+    let entries = read_cache_file(&environment, &file_path).unwrap_or_default();
+    Self {
+      environment,
+      file_path,
+      state: Mutex::new(CacheState { entries, dirty_keys: Default::default() }),
+    }
+  }
+
+  /// Gets a previously cached entry for `key`, discarding it if it can no
+  /// longer be deserialized (for example after a format change) rather than
+  /// failing the whole cache.
+  pub fn get(&self, key: u64) -> Option<(PluginInfo, FileMatchingInfo)> {
+    let raw = self.state.lock().unwrap().entries.get(&key).cloned()?;
+    match rmp_serde::from_slice::<CacheEntry>(&raw) {
+      Ok(entry) => Some((entry.info, entry.file_matching)),
+      Err(_) => {
+        self.discard(key);
+        None
+      }
+    }
+  }
+
+  /// Inserts or replaces the entry for `key`. A no-op if the exact same
+  /// entry is already cached, so unrelated plugins don't get marked dirty.
+  pub fn insert(&self, key: u64, info: PluginInfo, file_matching: FileMatchingInfo) {
+    let entry = CacheEntry { info, file_matching };
+    let Ok(raw) = rmp_serde::to_vec(&entry) else {
+      return;
+    };
+    let mut state = self.state.lock().unwrap();
+    if state.entries.get(&key) == Some(&raw) {
+      return;
+    }
+    state.entries.insert(key, raw);
+    state.dirty_keys.insert(key);
+  }
+
+  /// Discards a single entry, e.g. because it failed to deserialize.
+  pub fn discard(&self, key: u64) {
+    let mut state = self.state.lock().unwrap();
+    state.entries.remove(&key);
+    state.dirty_keys.insert(key);
+  }
+
+  /// Writes the cache to disk if any entry changed since it was loaded.
+  ///
+  /// This is a full rewrite of `state.entries`, not a true incremental write --
+  /// `dirty_keys` only gates *whether* we write, not *how much*. For a repo
+  /// with a handful of plugins this is cheap enough not to matter, but it
+  /// does mean `save()`'s cost scales with the whole cache, not with how much
+  /// actually changed. A real fix would need a per-key persisted format (e.g.
+  /// an append-only log of `(key, compressed entry)` records, replayed on
+  /// `load` with the last record per key winning) instead of one blob holding
+  /// every entry; that's more involved than this change and hasn't been done.
+  pub fn save(&self) -> Result<()> {
+    let mut state = self.state.lock().unwrap();
+    if state.dirty_keys.is_empty() {
+      return Ok(());
+    }
+    let bytes = rmp_serde::to_vec(&state.entries)?;
+    let compressed = compress_brotli(&bytes);
+    self.environment.write_file_bytes(&self.file_path, &compressed)?;
+    state.dirty_keys.clear();
+    Ok(())
+  }
+}
+
+fn read_cache_file<TEnvironment: Environment>(environment: &TEnvironment, file_path: &Path) -> Option<HashMap<u64, Vec<u8>>> {
+  let compressed = environment.read_file_bytes(file_path).ok()?;
+  let bytes = decompress_brotli(&compressed).ok()?;
+  rmp_serde::from_slice(&bytes).ok()
+}
+
+fn compress_brotli(bytes: &[u8]) -> Vec<u8> {
+  let mut output = Vec::new();
+  {
+    // quality 9, default window size -- this is a small cache file, not worth maxing out quality
+    let mut writer = brotli::CompressorWriter::new(&mut output, 4096, 9, 22);
+    // writing to an in-memory buffer never fails
+    writer.write_all(bytes).unwrap();
+  }
+  output
+}
+
+fn decompress_brotli(bytes: &[u8]) -> Result<Vec<u8>> {
+  let mut output = Vec::new();
+  brotli::Decompressor::new(bytes, 4096).read_to_end(&mut output)?;
+  Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn brotli_round_trips_arbitrary_bytes() {
+    let original = b"some plugin cache bytes, repeated, repeated, repeated".to_vec();
+    let compressed = compress_brotli(&original);
+    assert_eq!(decompress_brotli(&compressed).unwrap(), original);
+  }
+
+  #[test]
+  fn brotli_round_trips_empty_input() {
+    let compressed = compress_brotli(&[]);
+    assert_eq!(decompress_brotli(&compressed).unwrap(), Vec::<u8>::new());
+  }
+
+  #[test]
+  fn decompress_brotli_rejects_garbage() {
+    assert!(decompress_brotli(b"not brotli data").is_err());
+  }
+
+  #[test]
+  fn cache_entries_map_round_trips_through_msgpack() {
+    let mut entries = HashMap::new();
+    entries.insert(1u64, vec![1, 2, 3]);
+    entries.insert(2u64, vec![]);
+    let bytes = rmp_serde::to_vec(&entries).unwrap();
+    let decoded: HashMap<u64, Vec<u8>> = rmp_serde::from_slice(&bytes).unwrap();
+    assert_eq!(decoded, entries);
+  }
+}
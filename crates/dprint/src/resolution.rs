@@ -34,6 +34,7 @@ use crate::environment::Environment;
 use crate::paths::get_and_resolve_file_paths;
 use crate::paths::get_file_paths_by_plugins_and_err_if_empty;
 use crate::paths::PluginNames;
+use crate::plugin_cache::PluginCache;
 use crate::plugins::output_plugin_config_diagnostics;
 use crate::plugins::FormatConfig;
 use crate::plugins::InitializedPlugin;
@@ -78,11 +79,7 @@ impl PluginWithConfig {
     hasher.write(self.info().version.as_bytes());
 
     // serialize the config keys in order to prevent the hash from changing
-    let sorted_config = self.format_config.raw.iter().collect::<BTreeMap<_, _>>();
-    for (key, value) in sorted_config {
-      hasher.write(key.as_bytes());
-      value.hash(hasher);
-    }
+    hash_sorted_entries(hasher, self.format_config.raw.iter());
 
     if let Some(associations) = &self.associations {
       for association in associations {
@@ -294,12 +291,17 @@ pub async fn resolve_plugins_scope_and_paths<TEnvironment: Environment>(
     // to only specific plugins.
     check_top_level_unknown_property_diagnostics: args.plugins.is_empty(),
   };
+  // loaded once and shared across every (possibly concurrent) sub-config
+  // resolution this run does, so they all read/write the same in-memory
+  // state instead of clobbering each other's saves
+  let plugin_cache = PluginCache::load(environment.clone());
   let resolver = PluginsAndPathsResolver {
     args,
     patterns,
     environment,
     plugin_resolver,
     resolve_plugins_options: &resolve_plugins_options,
+    plugin_cache: &plugin_cache,
   };
 
   resolver.resolve_config().await
@@ -311,12 +313,13 @@ struct PluginsAndPathsResolver<'a, TEnvironment: Environment> {
   environment: &'a TEnvironment,
   plugin_resolver: &'a Rc<PluginResolver<TEnvironment>>,
   resolve_plugins_options: &'a ResolvePluginsOptions,
+  plugin_cache: &'a PluginCache<TEnvironment>,
 }
 
 impl<'a, TEnvironment: Environment> PluginsAndPathsResolver<'a, TEnvironment> {
   pub async fn resolve_config(&self) -> Result<Vec<PluginsScopeAndPaths<TEnvironment>>> {
     let config = Rc::new(resolve_config_from_args(self.args, self.environment).await?);
-    let scope = resolve_plugins_scope_and_err_if_empty(config.clone(), self.environment, self.plugin_resolver, self.resolve_plugins_options).await?;
+    let scope = resolve_plugins_scope_and_err_if_empty(config.clone(), self.environment, self.plugin_resolver, self.resolve_plugins_options, self.plugin_cache).await?;
     let glob_output = get_and_resolve_file_paths(&config, self.patterns, scope.plugins.values().map(|p| p.as_ref()), self.environment).await?;
     let file_paths_by_plugins = get_file_paths_by_plugins_and_err_if_empty(&config.base_path, &scope.plugin_name_maps, glob_output.file_paths)?;
 
@@ -345,7 +348,7 @@ impl<'a, TEnvironment: Environment> PluginsAndPathsResolver<'a, TEnvironment> {
         config.plugins = parent_config.plugins.clone();
       }
       let config = Rc::new(config);
-      let scope = resolve_plugins_scope_and_err_if_empty(config.clone(), self.environment, self.plugin_resolver, self.resolve_plugins_options).await?;
+      let scope = resolve_plugins_scope_and_err_if_empty(config.clone(), self.environment, self.plugin_resolver, self.resolve_plugins_options, self.plugin_cache).await?;
       let glob_output = get_and_resolve_file_paths(&config, self.patterns, scope.plugins.values().map(|p| p.as_ref()), self.environment).await?;
       let file_paths_by_plugins = get_file_paths_by_plugins_and_err_if_empty(&config.base_path, &scope.plugin_name_maps, glob_output.file_paths)?;
 
@@ -367,6 +370,7 @@ pub async fn get_plugins_scope_from_args<TEnvironment: Environment>(
 ) -> Result<PluginsScope<TEnvironment>, ResolvePluginsError> {
   match resolve_config_from_args(args, environment).await {
     Ok(config) => {
+      let plugin_cache = PluginCache::load(environment.clone());
       resolve_plugins_scope(
         Rc::new(config),
         environment,
@@ -377,6 +381,7 @@ pub async fn get_plugins_scope_from_args<TEnvironment: Environment>(
           // to only specific plugins.
           check_top_level_unknown_property_diagnostics: args.plugins.is_empty(),
         },
+        &plugin_cache,
       )
       .await
     }
@@ -407,8 +412,9 @@ pub async fn resolve_plugins_scope_and_err_if_empty<TEnvironment: Environment>(
   environment: &TEnvironment,
   plugin_resolver: &Rc<PluginResolver<TEnvironment>>,
   options: &ResolvePluginsOptions,
+  cache: &PluginCache<TEnvironment>,
 ) -> Result<PluginsScope<TEnvironment>> {
-  let scope = resolve_plugins_scope(config, environment, plugin_resolver, options).await?;
+  let scope = resolve_plugins_scope_inner(config, environment, plugin_resolver, options, None, cache).await?;
   if scope.plugins.is_empty() {
     Err(NoPluginsFoundError.into())
   } else {
@@ -421,6 +427,36 @@ pub async fn resolve_plugins_scope<TEnvironment: Environment>(
   environment: &TEnvironment,
   plugin_resolver: &Rc<PluginResolver<TEnvironment>>,
   options: &ResolvePluginsOptions,
+  cache: &PluginCache<TEnvironment>,
+) -> Result<PluginsScope<TEnvironment>, ResolvePluginsError> {
+  resolve_plugins_scope_inner(config, environment, plugin_resolver, options, None, cache).await
+}
+
+/// Like `resolve_plugins_scope`, but given the previously resolved scope for
+/// this same config, skips re-resolving (and re-initializing) any plugin
+/// whose `incremental_hash` is unchanged, reusing its `Rc<PluginWithConfig>`
+/// as-is. The language server's `ConfigTree` uses this to avoid redoing
+/// plugin initialization when a config file changes but only a single
+/// plugin's config actually changed. CLI runs, including `--incremental`
+/// ones, resolve without a previous scope.
+pub async fn resolve_plugins_scope_incremental<TEnvironment: Environment>(
+  config: Rc<ResolvedConfig>,
+  environment: &TEnvironment,
+  plugin_resolver: &Rc<PluginResolver<TEnvironment>>,
+  options: &ResolvePluginsOptions,
+  previous: &PluginsScope<TEnvironment>,
+  cache: &PluginCache<TEnvironment>,
+) -> Result<PluginsScope<TEnvironment>, ResolvePluginsError> {
+  resolve_plugins_scope_inner(config, environment, plugin_resolver, options, Some(previous), cache).await
+}
+
+async fn resolve_plugins_scope_inner<TEnvironment: Environment>(
+  config: Rc<ResolvedConfig>,
+  environment: &TEnvironment,
+  plugin_resolver: &Rc<PluginResolver<TEnvironment>>,
+  options: &ResolvePluginsOptions,
+  previous: Option<&PluginsScope<TEnvironment>>,
+  cache: &PluginCache<TEnvironment>,
 ) -> Result<PluginsScope<TEnvironment>, ResolvePluginsError> {
   // resolve the plugins
   let plugins = plugin_resolver.resolve_plugins(config.plugins.clone()).await?;
@@ -441,24 +477,42 @@ pub async fn resolve_plugins_scope<TEnvironment: Environment>(
     },
   )?;
 
-  // create the scope
+  // create the scope, reusing a plugin's previously cached state when its
+  // identity and config haven't changed since the last run
   let plugins = plugins_with_config.into_iter().map(|(plugin_config, plugin)| {
     let global_config = global_config.clone();
     let next_config_id = plugin_resolver.next_config_id();
     async move {
-      let instance = plugin.initialize().await?;
       let format_config = Arc::new(FormatConfig {
         id: next_config_id,
         global: global_config,
         raw: plugin_config.properties,
       });
+      let cache_key = plugin_cache_key(plugin.info(), &format_config, &plugin_config.associations);
+
+      // if this exact plugin (same identity and config) was already resolved
+      // last time around, reuse it without doing any work at all
+      if let Some(previous_plugin) = previous.and_then(|p| p.plugins.get(&plugin.info().name)) {
+        let mut hasher = FastInsecureHasher::default();
+        previous_plugin.incremental_hash(&mut hasher);
+        if hasher.finish() == cache_key {
+          return Ok::<_, anyhow::Error>(previous_plugin.clone());
+        }
+      }
+
+      // a persistent cache hit means we don't need to initialize the plugin at all up
+      // front; it'll be lazily initialized the first time it's actually used to format.
+      // config diagnostics aren't part of this cache (see `PluginCache`'s doc comment),
+      // so they're still checked lazily on that same first use, same as a cold miss below.
+      if let Some((_info, file_matching_info)) = cache.get(cache_key) {
+        return Ok::<_, anyhow::Error>(Rc::new(PluginWithConfig::new(plugin, plugin_config.associations, format_config, file_matching_info)));
+      }
+
+      let instance = plugin.initialize().await?;
       let file_matching_info = instance.file_matching_info(format_config.clone()).await?;
-      Ok::<_, anyhow::Error>(Rc::new(PluginWithConfig::new(
-        plugin,
-        plugin_config.associations,
-        format_config,
-        file_matching_info,
-      )))
+      let plugin_with_config = Rc::new(PluginWithConfig::new(plugin, plugin_config.associations, format_config, file_matching_info.clone()));
+      cache.insert(cache_key, plugin_with_config.info().clone(), file_matching_info);
+      Ok(plugin_with_config)
     }
     .boxed_local()
   });
@@ -467,6 +521,70 @@ pub async fn resolve_plugins_scope<TEnvironment: Environment>(
   for result in plugin_results {
     plugins.push(result?);
   }
+  if let Err(err) = cache.save() {
+    log_verbose!(environment, "Failed saving plugin cache: {:#}", err);
+  }
 
   Ok(PluginsScope::new(environment.clone(), plugins, config)?)
 }
+
+/// Computes the key used to look up a plugin's cached state: its identity
+/// plus everything that would affect formatting, mirroring `PluginWithConfig::incremental_hash`.
+fn plugin_cache_key(info: &PluginInfo, format_config: &FormatConfig, associations: &Option<Vec<String>>) -> u64 {
+  let mut hasher = FastInsecureHasher::default();
+  hasher.write(info.name.as_bytes());
+  hasher.write(info.version.as_bytes());
+
+  hash_sorted_entries(&mut hasher, format_config.raw.iter());
+
+  if let Some(associations) = associations {
+    for association in associations {
+      hasher.write(association.as_bytes());
+    }
+  }
+  use std::hash::Hash;
+  format_config.global.hash(&mut hasher);
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn hash_entries(entries: &HashMap<String, u32>) -> u64 {
+    let mut hasher = FastInsecureHasher::default();
+    hash_sorted_entries(&mut hasher, entries.iter());
+    hasher.finish()
+  }
+
+  #[test]
+  fn hash_sorted_entries_is_independent_of_map_iteration_order() {
+    // a `HashMap`'s iteration order isn't guaranteed to be stable across
+    // equivalent maps, which is exactly why the incremental cache key sorts
+    // by key first -- otherwise a plugin whose config didn't actually change
+    // could still compute a different hash and be needlessly re-initialized
+    let a: HashMap<String, u32> = [("a".to_string(), 1), ("b".to_string(), 2), ("c".to_string(), 3)].into_iter().collect();
+    let b: HashMap<String, u32> = [("c".to_string(), 3), ("a".to_string(), 1), ("b".to_string(), 2)].into_iter().collect();
+    assert_eq!(hash_entries(&a), hash_entries(&b));
+  }
+
+  #[test]
+  fn hash_sorted_entries_changes_when_a_value_changes() {
+    let original: HashMap<String, u32> = [("a".to_string(), 1)].into_iter().collect();
+    let changed: HashMap<String, u32> = [("a".to_string(), 2)].into_iter().collect();
+    assert_ne!(hash_entries(&original), hash_entries(&changed));
+  }
+}
+
+/// Hashes `entries` sorted by key rather than in iteration order, shared by
+/// `PluginWithConfig::incremental_hash` and `plugin_cache_key` so that a
+/// config map that hashes to a different iteration order each run (as a
+/// `HashMap` does) still produces the same hash as long as its contents
+/// didn't change.
+fn hash_sorted_entries<'a, V: std::hash::Hash + 'a>(hasher: &mut impl Hasher, entries: impl Iterator<Item = (&'a String, &'a V)>) {
+  let sorted = entries.collect::<BTreeMap<_, _>>();
+  for (key, value) in sorted {
+    hasher.write(key.as_bytes());
+    value.hash(hasher);
+  }
+}